@@ -1,4 +1,5 @@
 pub mod cached_jwk_set;
+pub mod composite;
 
 use std::{convert::Infallible, ops::Deref, sync::Arc};
 
@@ -6,16 +7,15 @@ use async_trait::async_trait;
 use axum::{
     Json,
     extract::{FromRequestParts, Request, State},
-    http::{StatusCode, request::Parts},
+    http::{HeaderMap, StatusCode, header, request::Parts},
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use axum_extra::TypedHeader;
-use headers::{Authorization, authorization::Bearer};
 use jsonwebtoken::{
-    DecodingKey, TokenData, Validation, decode, decode_header,
+    Algorithm, DecodingKey, TokenData, Validation, decode, decode_header,
     jwk::{AlgorithmParameters, JwkSet},
 };
+use serde::de::DeserializeOwned;
 
 #[derive(Debug, thiserror::Error)]
 pub enum AuthError {
@@ -49,14 +49,48 @@ impl IntoResponse for AuthError {
 }
 
 #[async_trait]
-pub trait AuthProvider: Send + Sync {
+pub trait AuthProvider<C = Claims>: Send + Sync
+where
+    C: DeserializeOwned + Clone + Send + Sync + 'static,
+{
     async fn jwk_set(&self) -> Result<JwkSet, AuthError>;
 
+    /// Force a fresh fetch of the key set, bypassing any cached entry.
+    ///
+    /// The default implementation is a no-op that returns
+    /// [`jwk_set`](Self::jwk_set); caching providers override it to survive
+    /// signing-key rotation.
+    async fn force_refresh(&self) -> Result<JwkSet, AuthError> {
+        self.jwk_set().await
+    }
+
     fn decode_validation(&self, validation: Validation) -> Validation {
         validation
     }
 
-    async fn verify(&self, token: &str) -> Result<TokenData<Claims>, AuthError> {
+    /// Algorithms the provider is willing to accept, or `None` to accept
+    /// whatever the token header asks for.
+    ///
+    /// When `Some`, [`verify`](Self::verify) rejects any token whose header
+    /// algorithm is absent from the list *before* a decoding key is selected,
+    /// closing the algorithm-confusion surface.
+    fn allowed_algorithms(&self) -> Option<Vec<Algorithm>> {
+        None
+    }
+
+    /// Verify a token and report which upstream issuer accepted it.
+    ///
+    /// The default implementation delegates to [`verify`](Self::verify) and
+    /// reports no issuer; multi-issuer providers override it so the middleware
+    /// can surface the match in request extensions.
+    async fn verify_issuer(
+        &self,
+        token: &str,
+    ) -> Result<(TokenData<C>, Option<String>), AuthError> {
+        Ok((self.verify(token).await?, None))
+    }
+
+    async fn verify(&self, token: &str) -> Result<TokenData<C>, AuthError> {
         let token_sections: Vec<&str> = token.split('.').collect();
         if token_sections.len() < 2 {
             return Err(AuthError::InvalidToken("invalid format".into()));
@@ -65,16 +99,33 @@ pub trait AuthProvider: Send + Sync {
         let header =
             decode_header(&token).map_err(|err| AuthError::InvalidToken(err.to_string()))?;
 
+        if let Some(allowed) = self.allowed_algorithms() {
+            if !allowed.contains(&header.alg) {
+                return Err(AuthError::UnsupportedAlgorithm);
+            }
+        }
+
         let jwk_set = self.jwk_set().await?;
 
         let Some(kid) = header.kid else {
             return Err(AuthError::InvalidToken("missing `kid` header field".into()));
         };
 
-        let Some(jwk) = jwk_set.find(&kid) else {
-            return Err(AuthError::InvalidToken(
-                "no matching JWK found for the given kid".into(),
-            ));
+        let jwk = match jwk_set.find(&kid) {
+            Some(jwk) => jwk.to_owned(),
+            // The cache may be holding a stale set right after the provider
+            // rotated its signing keys. Force a single refresh before giving up.
+            None => {
+                let jwk_set = self.force_refresh().await?;
+                jwk_set
+                    .find(&kid)
+                    .ok_or_else(|| {
+                        AuthError::InvalidToken(
+                            "no matching JWK found for the given kid".into(),
+                        )
+                    })?
+                    .to_owned()
+            }
         };
 
         let decoding_key = match &jwk.algorithm {
@@ -87,7 +138,7 @@ pub trait AuthProvider: Send + Sync {
 
         let validation = self.decode_validation(Validation::new(header.alg));
 
-        Ok(decode::<Claims>(token, &decoding_key, &validation)
+        Ok(decode::<C>(token, &decoding_key, &validation)
             .map_err(|err| AuthError::InvalidToken(err.to_string()))?)
     }
 }
@@ -98,33 +149,141 @@ pub struct Claims {
     pub exp: usize,
 }
 
-pub async fn auth_middleware(
-    State(auth_provider): State<Arc<dyn AuthProvider>>,
-    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+/// A source the middleware consults to pull the raw token string out of a
+/// request. Extractors are tried in order and the first match wins, so a single
+/// deployment can accept, say, both a bearer header and a session cookie.
+#[derive(Clone, Debug)]
+pub enum TokenExtractor {
+    /// The `Authorization: Bearer <token>` header.
+    Bearer,
+    /// A cookie carrying the token, identified by name (e.g. `access_token`).
+    Cookie(String),
+    /// A custom header carrying the bare token (e.g. `x-api-token`).
+    CustomHeader(String),
+}
+
+impl TokenExtractor {
+    fn extract(&self, headers: &HeaderMap) -> Option<String> {
+        match self {
+            TokenExtractor::Bearer => headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| {
+                    // The `Authorization` scheme is case-insensitive, so match
+                    // `bearer` regardless of case (`TypedHeader<Authorization>`
+                    // does the same) rather than a literal `Bearer ` prefix.
+                    let (scheme, token) = value.split_once(' ')?;
+                    scheme
+                        .eq_ignore_ascii_case("bearer")
+                        .then(|| token.trim().to_owned())
+                }),
+            TokenExtractor::Cookie(name) => headers
+                .get(header::COOKIE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|cookies| {
+                    cookies.split(';').find_map(|cookie| {
+                        let (key, value) = cookie.trim().split_once('=')?;
+                        (key == name).then(|| value.to_owned())
+                    })
+                }),
+            TokenExtractor::CustomHeader(name) => headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(|token| token.trim().to_owned()),
+        }
+    }
+}
+
+/// Bundles an [`AuthProvider`] with the ordered list of [`TokenExtractor`]s the
+/// middleware uses to locate a credential, and is the [`State`] consumed by
+/// [`auth_middleware`].
+pub struct Authenticator<C = Claims> {
+    provider: Arc<dyn AuthProvider<C>>,
+    extractors: Vec<TokenExtractor>,
+}
+
+impl<C> Authenticator<C>
+where
+    C: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    /// Create an authenticator that extracts a bearer token, matching the
+    /// historical `Authorization: Bearer` behaviour.
+    pub fn new(provider: Arc<dyn AuthProvider<C>>) -> Self {
+        Self {
+            provider,
+            extractors: vec![TokenExtractor::Bearer],
+        }
+    }
+
+    /// Replace the extractor list. Extractors are consulted in the given order.
+    pub fn extractors(mut self, extractors: Vec<TokenExtractor>) -> Self {
+        self.extractors = extractors;
+        self
+    }
+
+    /// Append a single extractor to the list.
+    pub fn extractor(mut self, extractor: TokenExtractor) -> Self {
+        self.extractors.push(extractor);
+        self
+    }
+
+    fn extract_token(&self, headers: &HeaderMap) -> Option<String> {
+        self.extractors
+            .iter()
+            .find_map(|extractor| extractor.extract(headers))
+    }
+}
+
+pub async fn auth_middleware<C>(
+    State(authenticator): State<Arc<Authenticator<C>>>,
     mut request: Request,
     next: Next,
-) -> Result<Response, Infallible> {
-    let claims = match auth_provider.verify(authorization.token()).await {
-        Ok(claims) => claims,
+) -> Result<Response, Infallible>
+where
+    C: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let Some(token) = authenticator.extract_token(request.headers()) else {
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(serde_json::json!({ "error": "Missing credentials" })),
+        )
+            .into_response());
+    };
+
+    let (claims, issuer) = match authenticator.provider.verify_issuer(&token).await {
+        Ok(verified) => verified,
         Err(err) => return Ok(err.into_response()),
     };
 
     request.extensions_mut().insert(claims);
+    if let Some(issuer) = issuer {
+        request.extensions_mut().insert(ValidatedIssuer(issuer));
+    }
 
     Ok(next.run(request).await)
 }
 
-pub struct Token(TokenData<Claims>);
+/// The issuer whose [`AuthProvider`] validated the request token, inserted into
+/// request extensions by [`auth_middleware`] when a multi-issuer provider
+/// resolves the match.
+#[derive(Clone, Debug)]
+pub struct ValidatedIssuer(pub String);
 
-impl Deref for Token {
-    type Target = TokenData<Claims>;
+pub struct Token<C = Claims>(TokenData<C>);
+
+impl<C> Deref for Token<C> {
+    type Target = TokenData<C>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-impl<S> FromRequestParts<S> for Token {
+impl<S, C> FromRequestParts<S> for Token<C>
+where
+    C: Clone + Send + Sync + 'static,
+{
     type Rejection = StatusCode;
     fn from_request_parts(
         parts: &mut Parts,
@@ -133,10 +292,153 @@ impl<S> FromRequestParts<S> for Token {
         async move {
             let token = parts
                 .extensions
-                .get::<TokenData<Claims>>()
+                .get::<TokenData<C>>()
                 .ok_or_else(|| StatusCode::INTERNAL_SERVER_ERROR)?;
 
             Ok(Token(token.to_owned()))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header, encode};
+
+    use super::*;
+
+    struct RejectingProvider;
+
+    #[async_trait]
+    impl AuthProvider<Claims> for RejectingProvider {
+        async fn jwk_set(&self) -> Result<JwkSet, AuthError> {
+            panic!("jwk_set must not be consulted once the algorithm is rejected")
+        }
+
+        fn allowed_algorithms(&self) -> Option<Vec<Algorithm>> {
+            Some(vec![Algorithm::ES256])
+        }
+    }
+
+    #[tokio::test]
+    async fn token_with_disallowed_algorithm_is_rejected_before_key_selection() {
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &Claims {
+                sub: "alice".into(),
+                exp: 0,
+            },
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        let err = RejectingProvider.verify(&token).await.unwrap_err();
+        assert!(matches!(err, AuthError::UnsupportedAlgorithm));
+    }
+
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn bearer_extractor_matches_scheme_case_insensitively() {
+        let extractor = TokenExtractor::Bearer;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer abc"));
+        assert_eq!(extractor.extract(&headers).as_deref(), Some("abc"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("bearer abc"));
+        assert_eq!(extractor.extract(&headers).as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn cookie_extractor_parses_named_cookie() {
+        let extractor = TokenExtractor::Cookie("access_token".into());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("foo=bar; access_token=xyz; baz=qux"),
+        );
+        assert_eq!(extractor.extract(&headers).as_deref(), Some("xyz"));
+
+        // A cookie header without the named entry yields nothing.
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("foo=bar"));
+        assert_eq!(extractor.extract(&headers), None);
+    }
+
+    #[test]
+    fn custom_header_extractor_reads_raw_value() {
+        let extractor = TokenExtractor::CustomHeader("x-api-token".into());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-token", HeaderValue::from_static("tok"));
+        assert_eq!(extractor.extract(&headers).as_deref(), Some("tok"));
+    }
+
+    #[test]
+    fn first_matching_extractor_wins() {
+        let authenticator = Authenticator::new(Arc::new(RejectingProvider) as Arc<dyn AuthProvider>)
+            .extractors(vec![
+                TokenExtractor::Cookie("access_token".into()),
+                TokenExtractor::Bearer,
+            ]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer from-header"),
+        );
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("access_token=from-cookie"),
+        );
+
+        // Cookie is listed first, so it wins over the bearer header.
+        assert_eq!(
+            authenticator.extract_token(&headers).as_deref(),
+            Some("from-cookie")
+        );
+    }
+
+    #[test]
+    fn no_credential_yields_no_token() {
+        // The condition that drives the middleware's 401 + `WWW-Authenticate`.
+        let authenticator = Authenticator::new(Arc::new(RejectingProvider) as Arc<dyn AuthProvider>);
+        assert_eq!(authenticator.extract_token(&HeaderMap::new()), None);
+    }
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct CustomClaims {
+        iss: String,
+        roles: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn token_extractor_round_trips_a_custom_claims_type() {
+        let data = TokenData {
+            header: Header::new(Algorithm::HS256),
+            claims: CustomClaims {
+                iss: "https://idp.test".into(),
+                roles: vec!["admin".into(), "user".into()],
+            },
+        };
+
+        // Stand in for the middleware inserting `TokenData<C>` into extensions.
+        let mut request = axum::http::Request::builder().body(()).unwrap();
+        request.extensions_mut().insert(data.clone());
+        let (mut parts, _) = request.into_parts();
+
+        let token = Token::<CustomClaims>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(token.claims, data.claims);
+
+        // Without the extension the extractor reports an internal error.
+        let (mut parts, _) = axum::http::Request::builder().body(()).unwrap().into_parts();
+        let rejection = Token::<CustomClaims>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap_err();
+        assert_eq!(rejection, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -1,12 +1,15 @@
 use std::{
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
 use http_client::{HttpClient, Request, RequestBuilderExt, ResponseAsyncBodyExt};
-use jsonwebtoken::{Validation, jwk::JwkSet};
-use tokio::sync::Mutex;
+use jsonwebtoken::{Algorithm, Validation, jwk::JwkSet};
+use tokio::sync::{Notify, RwLock};
 
 use crate::{AuthError, AuthProvider};
 
@@ -46,18 +49,196 @@ impl<T> From<(Duration, T)> for SingleCache<T> {
     }
 }
 
-pub struct CachedJwkSet {
+struct Inner {
     jwk_set_uri: String,
     duration: Duration,
-    validator: Arc<dyn Fn(Validation) -> Validation + Send + Sync>,
-    cached_keys: Arc<Mutex<SingleCache<JwkSet>>>,
+    force_refresh_cooldown: Duration,
     http_client: Arc<dyn HttpClient>,
+    cached_keys: RwLock<SingleCache<JwkSet>>,
+    last_forced: RwLock<Option<Instant>>,
+    refreshing: AtomicBool,
+    notify: Notify,
+}
+
+impl Inner {
+    // Fetch the JWKS with the entry lifetime taken from the response
+    // `Cache-Control: max-age`, falling back to the configured `duration`.
+    async fn fetch(&self) -> Result<(JwkSet, Duration), AuthError> {
+        let response = self
+            .http_client
+            .send(
+                Request::builder()
+                    .method(http_client::http::Method::GET)
+                    .uri(self.jwk_set_uri.clone())
+                    .end()
+                    .unwrap(),
+            )
+            .await
+            .map_err(|err| AuthError::MissingCredentials(err.to_string()))?;
+
+        let max_age = response
+            .headers()
+            .get(http_client::http::header::CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_max_age);
+
+        let jwk_set = response
+            .json::<JwkSet>()
+            .await
+            .map_err(|err| AuthError::MissingCredentials(err.to_string()))?;
+
+        Ok((jwk_set, max_age.unwrap_or(self.duration)))
+    }
+
+    async fn load_and_store(&self) -> Result<(), AuthError> {
+        let (jwk_set, duration) = self.fetch().await?;
+        *self.cached_keys.write().await = SingleCache::from((duration, jwk_set));
+        Ok(())
+    }
+
+    // First load: no cached set to fall back on, so callers must block. A burst
+    // collapses onto a single fetch — the winner performs it, the rest wait.
+    async fn load_blocking(&self) -> Result<(), AuthError> {
+        loop {
+            // Register for the wakeup before racing for the slot: `enable()`
+            // arms the `Notified` future now, so a winner that finishes and
+            // calls `notify_waiters()` before we park still wakes us.
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self
+                .refreshing
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let result = self.load_and_store().await;
+                self.refreshing.store(false, Ordering::Release);
+                self.notify.notify_waiters();
+                return result;
+            }
+
+            notified.await;
+            if !self.cached_keys.read().await.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    // Spawn at most one background refresh for a stale entry; losers of the
+    // race return straight away and keep serving the stale set.
+    fn spawn_refresh(self: &Arc<Self>) {
+        if self
+            .refreshing
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return;
+        }
+
+        let inner = self.clone();
+        tokio::spawn(async move {
+            let _ = inner.load_and_store().await;
+            inner.refreshing.store(false, Ordering::Release);
+            inner.notify.notify_waiters();
+        });
+    }
+
+    async fn jwk_set(self: &Arc<Self>) -> Result<JwkSet, AuthError> {
+        {
+            let guard = self.cached_keys.read().await;
+            if !guard.is_none() {
+                let stale = guard.is_expired();
+                let jwk_set = guard.inner().to_owned();
+                drop(guard);
+
+                if stale {
+                    self.spawn_refresh();
+                }
+
+                return Ok(jwk_set);
+            }
+        }
+
+        self.load_blocking().await?;
+        Ok(self.cached_keys.read().await.inner().to_owned())
+    }
+
+    // Force a refresh, bypassing the entry expiry. Concurrent forced refreshes
+    // collapse onto the same single-flight slot as `jwk_set`, and a successful
+    // fetch is rate-limited to one per `force_refresh_cooldown` so a flood of
+    // tokens carrying unknown `kid`s cannot hammer the JWKS endpoint — within
+    // the cooldown the currently cached set is returned unchanged.
+    async fn force_refresh(self: &Arc<Self>) -> Result<JwkSet, AuthError> {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self
+                .refreshing
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let within_cooldown = match *self.last_forced.read().await {
+                    Some(at) => at.elapsed() < self.force_refresh_cooldown,
+                    None => false,
+                };
+
+                let result = if within_cooldown {
+                    Ok(())
+                } else {
+                    let result = self.load_and_store().await;
+                    if result.is_ok() {
+                        *self.last_forced.write().await = Some(Instant::now());
+                    }
+                    result
+                };
+
+                self.refreshing.store(false, Ordering::Release);
+                self.notify.notify_waiters();
+                result?;
+                return Ok(self.cached_keys.read().await.inner().to_owned());
+            }
+
+            // A refresh is already in flight; wait for it instead of issuing a
+            // concurrent fetch, then serve whatever it stored.
+            notified.await;
+            let cached = self.cached_keys.read().await;
+            if !cached.is_none() {
+                return Ok(cached.inner().to_owned());
+            }
+        }
+    }
+}
+
+const DEFAULT_FORCE_REFRESH_COOLDOWN: Duration = Duration::from_secs(60);
+
+// Parse the `max-age` directive (in seconds) out of a `Cache-Control` value.
+fn parse_max_age(header: &str) -> Option<Duration> {
+    header.split(',').find_map(|directive| {
+        let directive = directive.trim();
+        let seconds = directive.strip_prefix("max-age=")?;
+        seconds.parse::<u64>().ok().map(Duration::from_secs)
+    })
+}
+
+pub struct CachedJwkSet {
+    validator: Option<Arc<dyn Fn(Validation) -> Validation + Send + Sync>>,
+    algorithms: Option<Vec<Algorithm>>,
+    issuer: Option<String>,
+    audience: Option<Vec<String>>,
+    inner: Arc<Inner>,
 }
 
 pub struct CachedJwkSetBuilder {
     jwk_set_uri: Option<String>,
     duration: Option<Duration>,
+    force_refresh_cooldown: Option<Duration>,
     validator: Option<Arc<dyn Fn(Validation) -> Validation + Send + Sync>>,
+    algorithms: Option<Vec<Algorithm>>,
+    issuer: Option<String>,
+    audience: Option<Vec<String>>,
     http_client: Option<Arc<dyn HttpClient>>,
 }
 
@@ -66,7 +247,11 @@ impl CachedJwkSet {
         CachedJwkSetBuilder {
             jwk_set_uri: None,
             duration: None,
+            force_refresh_cooldown: None,
             validator: None,
+            algorithms: None,
+            issuer: None,
+            audience: None,
             http_client: None,
         }
     }
@@ -83,6 +268,11 @@ impl CachedJwkSetBuilder {
         self
     }
 
+    pub fn force_refresh_cooldown(mut self, force_refresh_cooldown: Duration) -> Self {
+        self.force_refresh_cooldown = Some(force_refresh_cooldown);
+        self
+    }
+
     pub fn validator(
         mut self,
         validator: Arc<dyn Fn(Validation) -> Validation + Send + Sync>,
@@ -91,6 +281,21 @@ impl CachedJwkSetBuilder {
         self
     }
 
+    pub fn algorithms(mut self, algorithms: Vec<Algorithm>) -> Self {
+        self.algorithms = Some(algorithms);
+        self
+    }
+
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    pub fn audience(mut self, audience: Vec<String>) -> Self {
+        self.audience = Some(audience);
+        self
+    }
+
     pub fn http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
         self.http_client = Some(http_client);
         self
@@ -98,55 +303,252 @@ impl CachedJwkSetBuilder {
 
     pub fn build(&self) -> anyhow::Result<CachedJwkSet> {
         Ok(CachedJwkSet {
-            jwk_set_uri: self
-                .jwk_set_uri
-                .to_owned()
-                .ok_or_else(|| anyhow::anyhow!("Issuer is required".to_string()))?,
-            duration: self
-                .duration
-                .to_owned()
-                .ok_or_else(|| anyhow::anyhow!("Duration is required".to_string()))?,
-            validator: self
-                .validator
-                .to_owned()
-                .ok_or_else(|| anyhow::anyhow!("Validation is required".to_string()))?,
-            cached_keys: Arc::new(Mutex::new(SingleCache::default())),
-            http_client: self
-                .http_client
-                .to_owned()
-                .ok_or_else(|| anyhow::anyhow!("HTTP client is required".to_string()))?,
+            validator: self.validator.to_owned(),
+            algorithms: self.algorithms.to_owned(),
+            issuer: self.issuer.to_owned(),
+            audience: self.audience.to_owned(),
+            inner: Arc::new(Inner {
+                jwk_set_uri: self
+                    .jwk_set_uri
+                    .to_owned()
+                    .ok_or_else(|| anyhow::anyhow!("JWK set URI is required".to_string()))?,
+                duration: self
+                    .duration
+                    .to_owned()
+                    .ok_or_else(|| anyhow::anyhow!("Duration is required".to_string()))?,
+                http_client: self
+                    .http_client
+                    .to_owned()
+                    .ok_or_else(|| anyhow::anyhow!("HTTP client is required".to_string()))?,
+                force_refresh_cooldown: self
+                    .force_refresh_cooldown
+                    .unwrap_or(DEFAULT_FORCE_REFRESH_COOLDOWN),
+                cached_keys: RwLock::new(SingleCache::default()),
+                last_forced: RwLock::new(None),
+                refreshing: AtomicBool::new(false),
+                notify: Notify::new(),
+            }),
         })
     }
 }
 
 #[async_trait]
-impl AuthProvider for CachedJwkSet {
+impl<C> AuthProvider<C> for CachedJwkSet
+where
+    C: serde::de::DeserializeOwned + Clone + Send + Sync + 'static,
+{
     async fn jwk_set(&self) -> Result<JwkSet, AuthError> {
-        let mut cached_keys = self.cached_keys.lock().await;
-        if cached_keys.is_none() || cached_keys.is_expired() {
-            let jwk_set = self
-                .http_client
-                .send(
-                    Request::builder()
-                        .method(http_client::http::Method::GET)
-                        .uri(self.jwk_set_uri.clone())
-                        .end()
-                        .unwrap(),
-                )
-                .await
-                .map_err(|err| AuthError::MissingCredentials(err.to_string()))?
-                .json::<JwkSet>()
-                .await
-                .map_err(|err| AuthError::MissingCredentials(err.to_string()))?;
-
-            *cached_keys = SingleCache::from((self.duration, jwk_set));
+        self.inner.jwk_set().await
+    }
+
+    async fn force_refresh(&self) -> Result<JwkSet, AuthError> {
+        self.inner.force_refresh().await
+    }
+
+    fn allowed_algorithms(&self) -> Option<Vec<Algorithm>> {
+        self.algorithms.to_owned()
+    }
+
+    fn decode_validation(&self, mut validation: Validation) -> Validation {
+        if let Some(algorithms) = &self.algorithms {
+            validation.algorithms = algorithms.to_owned();
+        }
+
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        if let Some(audience) = &self.audience {
+            validation.set_audience(audience);
+        }
+
+        match &self.validator {
+            Some(validator) => validator(validation),
+            None => validation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    struct CountingClient {
+        body: &'static str,
+        max_age: Option<&'static str>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl HttpClient for CountingClient {
+        async fn send(
+            &self,
+            _request: http_client::Request,
+        ) -> anyhow::Result<http_client::Response> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut builder = http_client::http::Response::builder();
+            if let Some(max_age) = self.max_age {
+                builder = builder.header(http_client::http::header::CACHE_CONTROL, max_age);
+            }
+            Ok(builder.body(http_client::AsyncBody::from(self.body.as_bytes().to_vec()))?)
+        }
+    }
+
+    fn inner_with(duration: Duration, client: Arc<dyn HttpClient>) -> Arc<Inner> {
+        CachedJwkSet::builder()
+            .jwk_set_uri("https://issuer.test/jwks".to_string())
+            .duration(duration)
+            .http_client(client)
+            .build()
+            .unwrap()
+            .inner
+    }
+
+    #[test]
+    fn parse_max_age_reads_seconds() {
+        assert_eq!(
+            parse_max_age("public, max-age=600, must-revalidate"),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(parse_max_age("no-store"), None);
+    }
+
+    #[tokio::test]
+    async fn concurrent_first_load_collapses_to_single_fetch() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = inner_with(
+            Duration::from_secs(300),
+            Arc::new(CountingClient {
+                body: r#"{"keys":[]}"#,
+                max_age: None,
+                calls: calls.clone(),
+            }),
+        );
+
+        let mut handles = Vec::new();
+        for _ in 0..16 {
+            let inner = inner.clone();
+            handles.push(tokio::spawn(async move { inner.jwk_set().await.unwrap() }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_entry_serves_stale_and_refreshes_in_background() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = inner_with(
+            Duration::from_millis(0),
+            Arc::new(CountingClient {
+                body: r#"{"keys":[]}"#,
+                max_age: None,
+                calls: calls.clone(),
+            }),
+        );
+
+        // First read blocks and performs the only mandatory fetch.
+        inner.jwk_set().await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The entry is already expired, so the next read returns immediately
+        // from the stale set while spawning exactly one background refresh.
+        inner.jwk_set().await.unwrap();
+
+        let mut refreshed = false;
+        for _ in 0..50 {
+            if calls.load(Ordering::SeqCst) >= 2 {
+                refreshed = true;
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert!(refreshed, "background refresh did not run");
+    }
+
+    // Returns a different JWKS body on each call, modelling a provider rotating
+    // its signing keys between fetches.
+    struct RotatingClient {
+        bodies: Vec<String>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl HttpClient for RotatingClient {
+        async fn send(
+            &self,
+            _request: http_client::Request,
+        ) -> anyhow::Result<http_client::Response> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            let body = self.bodies[n.min(self.bodies.len() - 1)].clone();
+            Ok(http_client::http::Response::builder()
+                .body(http_client::AsyncBody::from(body.into_bytes()))?)
         }
-        Ok(cached_keys.inner().to_owned())
     }
 
-    fn decode_validation(&self, validation: Validation) -> Validation {
-        let validator = self.validator.clone();
+    // A valid P-256 public point (RFC 7515 A.3), so `from_ec_components`
+    // succeeds and the failure in `verify` lands past the `kid` lookup.
+    const EC_X: &str = "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU";
+    const EC_Y: &str = "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0";
+
+    fn jwks_with_kid(kid: &str) -> String {
+        format!(
+            r#"{{"keys":[{{"kty":"EC","crv":"P-256","kid":"{kid}","x":"{EC_X}","y":"{EC_Y}","alg":"ES256","use":"sig"}}]}}"#
+        )
+    }
+
+    // Signed with an HMAC secret, so the header must name an HMAC algorithm:
+    // `encode` rejects a header algorithm whose family differs from the key's.
+    // `verify` then fails on the key-family mismatch, after the `kid` lookup.
+    fn token_with_kid(kid: &str) -> String {
+        let mut header = jsonwebtoken::Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        jsonwebtoken::encode(
+            &header,
+            &crate::Claims {
+                sub: "subject".into(),
+                exp: 9_999_999_999,
+            },
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn unknown_kid_forces_single_refresh_then_cooldown_suppresses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = CachedJwkSet::builder()
+            .jwk_set_uri("https://issuer.test/jwks".to_string())
+            .duration(Duration::from_secs(300))
+            .http_client(Arc::new(RotatingClient {
+                bodies: vec![jwks_with_kid("key-1"), jwks_with_kid("key-2")],
+                calls: calls.clone(),
+            }))
+            .build()
+            .unwrap();
+
+        // `key-2` only appears after the rotation (the second fetch): the
+        // unknown kid must trigger exactly one forced refresh and then find it.
+        let err = AuthProvider::<crate::Claims>::verify(&provider, &token_with_kid("key-2"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(
+            !err.contains("no matching JWK"),
+            "rotated key should have been found: {err}"
+        );
 
-        validator(validation)
+        // A second unknown kid within the cooldown must not hit the endpoint.
+        let err = AuthProvider::<crate::Claims>::verify(&provider, &token_with_kid("key-3"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(err.contains("no matching JWK"));
     }
 }
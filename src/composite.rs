@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use jsonwebtoken::{
+    DecodingKey, TokenData, Validation, decode, decode_header, jwk::JwkSet,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{AuthError, AuthProvider, Claims};
+
+/// Validates tokens signed by any of several upstream issuers behind a single
+/// middleware, routing each token to the matching [`AuthProvider`].
+///
+/// Providers are keyed by their expected `iss` claim. On verification the
+/// unverified issuer is peeked out of the token and, when it names a configured
+/// provider, the token is routed straight to it; otherwise every provider is
+/// tried in turn. Because it implements [`AuthProvider`] itself it drops
+/// straight into [`auth_middleware`](crate::auth_middleware).
+pub struct CompositeAuthProvider<C = Claims> {
+    providers: Vec<(String, Arc<dyn AuthProvider<C>>)>,
+}
+
+pub struct CompositeAuthProviderBuilder<C = Claims> {
+    providers: Vec<(String, Arc<dyn AuthProvider<C>>)>,
+}
+
+impl<C> CompositeAuthProvider<C> {
+    pub fn builder() -> CompositeAuthProviderBuilder<C> {
+        CompositeAuthProviderBuilder {
+            providers: Vec::new(),
+        }
+    }
+}
+
+impl<C> CompositeAuthProviderBuilder<C> {
+    /// Register a provider under the issuer (`iss`) whose tokens it validates.
+    pub fn provider(
+        mut self,
+        issuer: impl Into<String>,
+        provider: Arc<dyn AuthProvider<C>>,
+    ) -> Self {
+        self.providers.push((issuer.into(), provider));
+        self
+    }
+
+    pub fn build(self) -> CompositeAuthProvider<C> {
+        CompositeAuthProvider {
+            providers: self.providers,
+        }
+    }
+}
+
+// Read the `iss` claim without verifying the signature, so it can route to the
+// right provider before a key is selected.
+fn peek_issuer(token: &str) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct IssuerClaim {
+        iss: Option<String>,
+    }
+
+    let header = decode_header(token).ok()?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    decode::<IssuerClaim>(token, &DecodingKey::from_secret(&[]), &validation)
+        .ok()?
+        .claims
+        .iss
+}
+
+#[async_trait]
+impl<C> AuthProvider<C> for CompositeAuthProvider<C>
+where
+    C: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    async fn jwk_set(&self) -> Result<JwkSet, AuthError> {
+        let mut keys = Vec::new();
+        for (_, provider) in &self.providers {
+            keys.extend(provider.jwk_set().await?.keys);
+        }
+        Ok(JwkSet { keys })
+    }
+
+    async fn verify(&self, token: &str) -> Result<TokenData<C>, AuthError> {
+        Ok(self.verify_issuer(token).await?.0)
+    }
+
+    async fn verify_issuer(
+        &self,
+        token: &str,
+    ) -> Result<(TokenData<C>, Option<String>), AuthError> {
+        // Route straight to the matching provider when the unverified issuer
+        // names one; a failure there is reported as-is rather than masked by a
+        // fallback attempt against an unrelated issuer.
+        if let Some(issuer) = peek_issuer(token) {
+            if let Some((name, provider)) =
+                self.providers.iter().find(|(key, _)| *key == issuer)
+            {
+                return Ok((provider.verify(token).await?, Some(name.to_owned())));
+            }
+        }
+
+        // Ambiguous or unknown issuer: try each provider in order.
+        let mut last_error = None;
+        for (name, provider) in &self.providers {
+            match provider.verify(token).await {
+                Ok(claims) => return Ok((claims, Some(name.to_owned()))),
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            AuthError::InvalidToken("no configured issuer accepted the token".into())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+
+    use super::*;
+
+    struct StubProvider {
+        accept: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AuthProvider<Claims> for StubProvider {
+        async fn jwk_set(&self) -> Result<JwkSet, AuthError> {
+            Ok(JwkSet { keys: Vec::new() })
+        }
+
+        async fn verify(&self, _token: &str) -> Result<TokenData<Claims>, AuthError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.accept {
+                Ok(TokenData {
+                    header: Header::new(Algorithm::HS256),
+                    claims: Claims {
+                        sub: "subject".into(),
+                        exp: 0,
+                    },
+                })
+            } else {
+                Err(AuthError::InvalidToken("stub rejects".into()))
+            }
+        }
+    }
+
+    fn token_with_issuer(issuer: Option<&str>) -> String {
+        #[derive(serde::Serialize)]
+        struct TestClaims<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            iss: Option<&'a str>,
+            sub: &'a str,
+            exp: usize,
+        }
+
+        encode(
+            &Header::new(Algorithm::HS256),
+            &TestClaims {
+                iss: issuer,
+                sub: "subject",
+                exp: 0,
+            },
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn routes_to_matching_issuer_without_trying_others() {
+        let matched = Arc::new(AtomicUsize::new(0));
+        let other = Arc::new(AtomicUsize::new(0));
+
+        let composite = CompositeAuthProvider::<Claims>::builder()
+            .provider(
+                "https://issuer-a.test",
+                Arc::new(StubProvider {
+                    accept: true,
+                    calls: matched.clone(),
+                }),
+            )
+            .provider(
+                "https://issuer-b.test",
+                Arc::new(StubProvider {
+                    accept: true,
+                    calls: other.clone(),
+                }),
+            )
+            .build();
+
+        let token = token_with_issuer(Some("https://issuer-a.test"));
+        let (_claims, issuer) = composite.verify_issuer(&token).await.unwrap();
+
+        assert_eq!(issuer.as_deref(), Some("https://issuer-a.test"));
+        assert_eq!(matched.load(Ordering::SeqCst), 1);
+        assert_eq!(other.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_each_provider_for_unknown_issuer() {
+        let first = Arc::new(AtomicUsize::new(0));
+        let second = Arc::new(AtomicUsize::new(0));
+
+        let composite = CompositeAuthProvider::<Claims>::builder()
+            .provider(
+                "https://issuer-a.test",
+                Arc::new(StubProvider {
+                    accept: false,
+                    calls: first.clone(),
+                }),
+            )
+            .provider(
+                "https://issuer-b.test",
+                Arc::new(StubProvider {
+                    accept: true,
+                    calls: second.clone(),
+                }),
+            )
+            .build();
+
+        let token = token_with_issuer(None);
+        let (_claims, issuer) = composite.verify_issuer(&token).await.unwrap();
+
+        assert_eq!(issuer.as_deref(), Some("https://issuer-b.test"));
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+    }
+}